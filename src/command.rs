@@ -0,0 +1,324 @@
+use std::ffi::CString;
+use std::io::Read;
+use std::os::unix::io::FromRawFd;
+use std::sync::Mutex;
+
+use libc::c_int;
+
+use crate::error::RashError;
+use crate::wrapper::{LibC, LibCWrapper};
+
+/// Serializes access to the real fd 2, which [`run_with`] temporarily
+/// redirects. fd 2 is shared by the whole process, so two concurrent calls
+/// redirecting it at once would race.
+static STDERR_REDIRECT_LOCK: Mutex<()> = Mutex::new(());
+
+/// The captured output of a command run through [`run`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Output {
+    /// Everything the command wrote to stdout.
+    pub stdout: String,
+    /// Everything the command wrote to stderr.
+    pub stderr: String,
+}
+
+/// Runs `command` through the system shell (via `popen`), capturing stdout
+/// and stderr separately.
+///
+/// stderr is captured using the same fd-redirection primitives
+/// [`std::process::Command`] is built on: the real fd 2 is saved with
+/// `dup`, fd 2 is redirected onto a fresh pipe with `dup2`, and the saved
+/// fd is restored once the command has finished.
+///
+/// With the `memfd-capture` feature enabled, this goes through
+/// `run_with_memfd` instead, which forks and execs `/bin/sh -c` directly
+/// rather than using `popen` and redirecting the parent's fd 2.
+pub fn run(command: &str) -> Result<Output, RashError> {
+    #[cfg(feature = "memfd-capture")]
+    {
+        return run_with_memfd(&LibC, command);
+    }
+
+    #[allow(unreachable_code)]
+    run_with(&LibC, command)
+}
+
+pub(crate) fn run_with<L: LibCWrapper>(wrapper: &L, command: &str) -> Result<Output, RashError> {
+    let c_command = CString::new(command).map_err(|e| RashError::NullByteInCommand {
+        message: format!("Null byte found at position {}", e.nul_position()),
+    })?;
+
+    let mut stderr_fds: [c_int; 2] = [0; 2];
+    if unsafe { wrapper.pipe(stderr_fds.as_mut_ptr()) } == -1 {
+        return Err(RashError::format_kernel_error_message(
+            wrapper,
+            "Failed to create a pipe to capture stderr",
+        ));
+    }
+    let [stderr_read_fd, stderr_write_fd] = stderr_fds;
+
+    // `popen` forks+execs the command, which would otherwise inherit both
+    // pipe fds: `dup2` below clears `FD_CLOEXEC` on whichever one lands on
+    // fd 2 (the real stderr redirect still needs to survive exec), while
+    // the other one, and the pipe's read end, close themselves at exec
+    // instead of leaking into the child.
+    unsafe {
+        libc::fcntl(stderr_read_fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        libc::fcntl(stderr_write_fd, libc::F_SETFD, libc::FD_CLOEXEC);
+    }
+
+    let guard = STDERR_REDIRECT_LOCK.lock().unwrap();
+
+    let saved_stderr_fd = unsafe { wrapper.dup(libc::STDERR_FILENO) };
+    if saved_stderr_fd == -1 {
+        drop(guard);
+        unsafe { libc::close(stderr_read_fd) };
+        unsafe { libc::close(stderr_write_fd) };
+        return Err(RashError::format_kernel_error_message(
+            wrapper,
+            "Failed to save the real stderr with dup()",
+        ));
+    }
+    unsafe { wrapper.dup2(stderr_write_fd, libc::STDERR_FILENO) };
+
+    let stream = unsafe { wrapper.popen(c_command.as_ptr()) };
+
+    // `popen` has forked by now, so the child holds its own copy of the
+    // write end; the real stderr can be restored and the lock released
+    // immediately instead of being held across the (potentially slow)
+    // output capture below.
+    unsafe {
+        wrapper.dup2(saved_stderr_fd, libc::STDERR_FILENO);
+        libc::close(saved_stderr_fd);
+        libc::close(stderr_write_fd);
+    }
+    drop(guard);
+
+    if stream.is_null() {
+        unsafe { libc::close(stderr_read_fd) };
+        return Err(RashError::format_kernel_error_message(
+            wrapper,
+            "Failed to run popen() on the given command",
+        ));
+    }
+
+    // stdout and stderr must be drained concurrently: the child can block
+    // writing more than a pipe buffer's worth of output to either stream,
+    // and if the other end isn't being read at the same time, `pclose`
+    // would then never see the child exit.
+    let stderr_handle = std::thread::spawn(move || {
+        capture_fd(stderr_read_fd, |message, source| RashError::FailedToReadStderr {
+            message,
+            source,
+        })
+    });
+
+    let owned_stdout_fd = unsafe { libc::dup(wrapper.fileno(stream)) };
+    let stdout_result = capture_fd(owned_stdout_fd, |message, source| {
+        RashError::FailedToReadStdout { message, source }
+    });
+    unsafe { wrapper.pclose(stream) };
+
+    let stderr_result = stderr_handle
+        .join()
+        .expect("stderr capture thread panicked");
+
+    Ok(Output {
+        stdout: stdout_result?,
+        stderr: stderr_result?,
+    })
+}
+
+/// Runs `command`, capturing stdout through a `memfd_create`-backed
+/// in-memory file instead of a `popen` pipe.
+///
+/// A pipe-based capture can deadlock once the command writes more than a
+/// pipe buffer's worth of output before the reader drains it. Writing
+/// stdout into an in-memory file removes that requirement for stdout,
+/// which is read back in one `mmap`'d shot instead of being drained
+/// concurrently; stderr still goes through a pipe, so it's drained on a
+/// background thread while we wait for the child. Falls back to
+/// [`run_with`] when `memfd_create` isn't available (e.g. an older kernel
+/// or a seccomp filter blocking it).
+#[cfg(feature = "memfd-capture")]
+pub(crate) fn run_with_memfd<L: LibCWrapper>(
+    wrapper: &L,
+    command: &str,
+) -> Result<Output, RashError> {
+    let c_command = CString::new(command).map_err(|e| RashError::NullByteInCommand {
+        message: format!("Null byte found at position {}", e.nul_position()),
+    })?;
+
+    let memfd_name = CString::new("rash-output").unwrap();
+    let memfd = unsafe { wrapper.memfd_create(memfd_name.as_ptr(), libc::MFD_CLOEXEC) };
+    if memfd == -1 {
+        return run_with(wrapper, command);
+    }
+
+    let mut stderr_fds: [c_int; 2] = [0; 2];
+    if unsafe { wrapper.pipe(stderr_fds.as_mut_ptr()) } == -1 {
+        unsafe { libc::close(memfd) };
+        return Err(RashError::format_kernel_error_message(
+            wrapper,
+            "Failed to create a pipe to capture stderr",
+        ));
+    }
+    let [stderr_read_fd, stderr_write_fd] = stderr_fds;
+
+    // This thread still has to `fork()`+`close()` its own way to a clean
+    // child below, but marking these CLOEXEC now closes the window where
+    // an unrelated exec from another thread in the host process (or a
+    // concurrent `run()`/`run_with()` call) could inherit them in the
+    // meantime; see `run_with`'s equivalent fix for its stderr pipe.
+    unsafe {
+        libc::fcntl(stderr_read_fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        libc::fcntl(stderr_write_fd, libc::F_SETFD, libc::FD_CLOEXEC);
+    }
+
+    let shell = CString::new("/bin/sh").unwrap();
+    let shell_flag = CString::new("-c").unwrap();
+
+    match unsafe { libc::fork() } {
+        -1 => {
+            unsafe {
+                libc::close(memfd);
+                libc::close(stderr_read_fd);
+                libc::close(stderr_write_fd);
+            }
+            Err(RashError::format_kernel_error_message(
+                wrapper,
+                "Failed to fork() the command",
+            ))
+        }
+        0 => unsafe {
+            wrapper.dup2(memfd, libc::STDOUT_FILENO);
+            wrapper.dup2(stderr_write_fd, libc::STDERR_FILENO);
+            // The originals are now redundant with fd 1/2's new targets
+            // (or, for `stderr_read_fd`, not needed by the child at all);
+            // close them so the executed command doesn't inherit a raw
+            // handle onto rash's memfd or either end of its stderr pipe.
+            libc::close(memfd);
+            libc::close(stderr_read_fd);
+            libc::close(stderr_write_fd);
+            libc::execl(
+                shell.as_ptr(),
+                shell.as_ptr(),
+                shell_flag.as_ptr(),
+                c_command.as_ptr(),
+                std::ptr::null::<libc::c_char>(),
+            );
+            libc::_exit(127);
+        },
+        child => {
+            unsafe { libc::close(stderr_write_fd) };
+
+            // stdout never touches a pipe here, but stderr still does: it
+            // must be drained concurrently with waiting for the child, or
+            // a child writing more than a pipe buffer's worth of stderr
+            // before we reap it would block on write(2) and `waitpid`
+            // would never return.
+            let stderr_handle = std::thread::spawn(move || {
+                capture_fd(stderr_read_fd, |message, source| RashError::FailedToReadStderr {
+                    message,
+                    source,
+                })
+            });
+
+            let mut status: c_int = 0;
+            unsafe { libc::waitpid(child, &mut status, 0) };
+
+            let stderr = stderr_handle
+                .join()
+                .expect("stderr capture thread panicked");
+
+            let written = unsafe { libc::lseek(memfd, 0, libc::SEEK_CUR) };
+            let stdout = if written < 0 {
+                Err(RashError::format_kernel_error_message(
+                    wrapper,
+                    "Failed to determine the captured output length",
+                ))
+            } else {
+                unsafe { wrapper.ftruncate(memfd, written) };
+                read_memfd(wrapper, memfd, written as usize)
+            };
+            unsafe { libc::close(memfd) };
+
+            Ok(Output {
+                stdout: stdout?,
+                stderr: stderr?,
+            })
+        }
+    }
+}
+
+/// `mmap`s `size` bytes of `fd` and copies them into a `String` in one shot.
+#[cfg(feature = "memfd-capture")]
+fn read_memfd<L: LibCWrapper>(wrapper: &L, fd: c_int, size: usize) -> Result<String, RashError> {
+    if size == 0 {
+        return Ok(String::new());
+    }
+
+    let ptr = unsafe {
+        wrapper.mmap(
+            std::ptr::null_mut(),
+            size,
+            libc::PROT_READ,
+            libc::MAP_PRIVATE,
+            fd,
+            0,
+        )
+    };
+    if ptr == libc::MAP_FAILED {
+        return Err(RashError::format_kernel_error_message(
+            wrapper,
+            "Failed to mmap() the captured output",
+        ));
+    }
+
+    let bytes = unsafe { std::slice::from_raw_parts(ptr as *const u8, size) };
+    let result = String::from_utf8(bytes.to_vec()).map_err(|e| {
+        let message = e.to_string();
+        RashError::FailedToReadStdout {
+            message,
+            source: Box::new(e.utf8_error()),
+        }
+    });
+    unsafe { libc::munmap(ptr, size) };
+    result
+}
+
+/// Reads `fd` to completion and decodes it as UTF-8, taking ownership of the
+/// fd (it is closed once the `File` built from it is dropped).
+fn capture_fd<F>(fd: c_int, to_error: F) -> Result<String, RashError>
+where
+    F: Fn(String, Box<dyn std::error::Error + Send + Sync>) -> RashError,
+{
+    let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf)
+        .map_err(|e| to_error(e.to_string(), Box::new(e)))?;
+    String::from_utf8(buf).map_err(|e| {
+        let message = e.to_string();
+        to_error(message, Box::new(e.utf8_error()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_captures_stdout_and_stderr_separately() {
+        let output = run("echo out; echo err 1>&2").unwrap();
+        assert_eq!(output.stdout, "out\n");
+        assert_eq!(output.stderr, "err\n");
+    }
+
+    #[cfg(feature = "memfd-capture")]
+    #[test]
+    fn test_run_with_memfd_captures_stdout_and_stderr_separately() {
+        let output = run_with_memfd(&LibC, "echo out; echo err 1>&2").unwrap();
+        assert_eq!(output.stdout, "out\n");
+        assert_eq!(output.stderr, "err\n");
+    }
+}
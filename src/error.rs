@@ -1,12 +1,114 @@
+use std::error::Error as StdError;
+#[cfg(not(feature = "no-alloc"))]
 use std::ffi::CStr;
 
+use libc::c_int;
 use thiserror::Error;
 
 use crate::wrapper::LibCWrapper;
 
+/// A decoded POSIX `errno` value.
+///
+/// This only covers the values rash's callers most commonly need to branch
+/// on (e.g. retrying on `EINTR` or surfacing a friendly message on
+/// `ENOENT`/`EACCES`). Any other raw errno is still available via
+/// [`RashError::errno`], it just won't have a matching variant here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Errno {
+    /// `EPERM`: Operation not permitted.
+    Eperm,
+    /// `ENOENT`: No such file or directory.
+    Enoent,
+    /// `EINTR`: Interrupted system call.
+    Eintr,
+    /// `EAGAIN`: Resource temporarily unavailable.
+    Eagain,
+    /// `ENOMEM`: Cannot allocate memory.
+    Enomem,
+    /// `EACCES`: Permission denied.
+    Eacces,
+    /// `EMFILE`: Too many open files.
+    Emfile,
+    /// `ENFILE`: Too many open files in system.
+    Enfile,
+    /// `EPIPE`: Broken pipe.
+    Epipe,
+    /// `ECHILD`: No child processes.
+    Echild,
+}
+
+impl Errno {
+    fn from_raw(errno: c_int) -> Option<Self> {
+        match errno {
+            libc::EPERM => Some(Errno::Eperm),
+            libc::ENOENT => Some(Errno::Enoent),
+            libc::EINTR => Some(Errno::Eintr),
+            libc::EAGAIN => Some(Errno::Eagain),
+            libc::ENOMEM => Some(Errno::Enomem),
+            libc::EACCES => Some(Errno::Eacces),
+            libc::EMFILE => Some(Errno::Emfile),
+            libc::ENFILE => Some(Errno::Enfile),
+            libc::EPIPE => Some(Errno::Epipe),
+            libc::ECHILD => Some(Errno::Echild),
+            _ => None,
+        }
+    }
+}
+
+/// The largest `strerror()` message [`StrError`] will store verbatim.
+///
+/// Every glibc `errno` description fits comfortably under this, so
+/// messages aren't truncated in practice; it's kept small (rather than
+/// glibc's own, much larger `strerror_r` buffer) so [`RashError`] stays
+/// cheap to return under the `no-alloc` feature — see the size note on
+/// [`RashError::KernelError`].
+#[cfg(feature = "no-alloc")]
+const STRERROR_BUF_LEN: usize = 64;
+
+/// An owned, fixed-capacity `strerror()` message, captured via
+/// `strerror_r` into a stack buffer at construction time.
+///
+/// Used by [`RashError::KernelError`] under the `no-alloc` feature, where
+/// the error path must not touch the heap. Earlier this held the raw
+/// pointer `strerror()` returns instead, but that pointer is only valid
+/// until the next `strerror`/`strerror_r` call on the thread (or a locale
+/// change), so formatting it lazily in `Display` could read a message
+/// libc had already overwritten. Copying the bytes out immediately avoids
+/// that without allocating.
+#[cfg(feature = "no-alloc")]
+#[derive(Debug, Clone, Copy)]
+pub struct StrError {
+    buf: [u8; STRERROR_BUF_LEN],
+    // `STRERROR_BUF_LEN` fits in a `u8`; using one instead of a `usize`
+    // keeps this struct, and so `RashError::KernelError`, small.
+    len: u8,
+}
+
+#[cfg(feature = "no-alloc")]
+impl StrError {
+    fn capture<L: LibCWrapper>(wrapper: &L, errno: c_int) -> Self {
+        let mut buf = [0u8; STRERROR_BUF_LEN];
+        unsafe {
+            wrapper.strerror_r(errno, buf.as_mut_ptr() as *mut libc::c_char, buf.len());
+        }
+        let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len()) as u8;
+        StrError { buf, len }
+    }
+}
+
+#[cfg(feature = "no-alloc")]
+impl std::fmt::Display for StrError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match std::str::from_utf8(&self.buf[..self.len as usize]) {
+            Ok(s) => f.write_str(s),
+            Err(_) => f.write_str("<strerror output was not valid UTF-8>"),
+        }
+    }
+}
+
 /// The error thrown if something went wrong in the processing of the command.
 #[cfg(unix)]
-#[derive(Error, Debug, PartialEq)]
+#[derive(Error, Debug)]
 pub enum RashError {
     /// The given command contained a null byte.
     /// Commands must **not** contain null bytes as they're converted into CStrings.
@@ -20,35 +122,138 @@ pub enum RashError {
     /// A system call failed.
     ///
     /// If this error is thrown, the error message will contain the errno,
-    /// a description of syscall that failed, and the strerror output.
-    #[error("{:?}", message)]
+    /// a description of syscall that failed, and the strerror output. The
+    /// raw `errno` and, where recognised, a decoded [`Errno`] are kept
+    /// alongside the message so callers can branch on the failure
+    /// programmatically instead of string-matching it; see
+    /// [`RashError::errno`] and [`RashError::is`].
+    ///
+    /// With the `no-alloc` feature enabled, this variant doesn't own a heap
+    /// `String`: it keeps the raw errno, a `&'static str` description, and
+    /// a [`StrError`] that formats `strerror`'s output directly. `StrError`
+    /// holds a small fixed-size buffer rather than glibc's own (much
+    /// larger) `strerror_r` buffer, so that a bare `Result<_, RashError>`
+    /// stays cheap to return even under `no-alloc`.
+    #[cfg_attr(not(feature = "no-alloc"), error("{:?}", message))]
+    #[cfg_attr(
+        feature = "no-alloc",
+        error(
+            "Received errno {}, Description: {}, strerror output: {}.",
+            errno,
+            description,
+            strerror
+        )
+    )]
     KernelError {
+        #[cfg(not(feature = "no-alloc"))]
         message: String,
+        #[cfg(feature = "no-alloc")]
+        description: &'static str,
+        #[cfg(feature = "no-alloc")]
+        strerror: StrError,
+        errno: c_int,
+        errno_kind: Option<Errno>,
     },
     /// We couldn't obtain stdout.
     /// This can occur if the stdout is not valid UTF-8
     /// or for any standard IO error kind.
     ///
     /// If this error is thrown, the error message will be the error message
-    /// given by calling `to_string()` on the source error.
+    /// given by calling `to_string()` on the source error. The original
+    /// `std::io::Error` or `std::str::Utf8Error` is kept as the
+    /// [`std::error::Error::source`] of this error, so callers can still
+    /// traverse the error chain and `downcast_ref` it.
     #[error("Couldn't read stdout: {:?}", message)]
     FailedToReadStdout {
         message: String,
+        #[source]
+        source: Box<dyn StdError + Send + Sync>,
     },
     /// We couldn't obtain stderr.
     /// This can occur if the stderr is not valid UTF-8
     /// or for any standard IO error kind.
     ///
     /// If this error is thrown, the error message will be the error message
-    /// given by calling `to_string()` on the source error.
+    /// given by calling `to_string()` on the source error. The original
+    /// `std::io::Error` or `std::str::Utf8Error` is kept as the
+    /// [`std::error::Error::source`] of this error, so callers can still
+    /// traverse the error chain and `downcast_ref` it.
     #[error("Couldn't read stderr: {:?}", message)]
     FailedToReadStderr {
         message: String,
+        #[source]
+        source: Box<dyn StdError + Send + Sync>,
+    },
+    /// Captured output (stdout or stderr) contained a null byte, so it
+    /// can't be handed back through the FFI layer's NUL-terminated C
+    /// strings.
+    ///
+    /// If this error is thrown, the error message will name which stream
+    /// it came from and the position of the null byte.
+    #[error("Null byte in {} output: {:?}", stream, message)]
+    NullByteInOutput {
+        stream: &'static str,
+        message: String,
     },
 }
 
+impl PartialEq for RashError {
+    /// Compares errors by their displayable fields. `FailedToReadStdout`/
+    /// `FailedToReadStderr`'s boxed `source` isn't `PartialEq` (it's a
+    /// trait object), so it's excluded from the comparison.
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::NullByteInCommand { message: a }, Self::NullByteInCommand { message: b }) => {
+                a == b
+            }
+            #[cfg(not(feature = "no-alloc"))]
+            (
+                Self::KernelError {
+                    message: a,
+                    errno: ea,
+                    errno_kind: ka,
+                },
+                Self::KernelError {
+                    message: b,
+                    errno: eb,
+                    errno_kind: kb,
+                },
+            ) => a == b && ea == eb && ka == kb,
+            #[cfg(feature = "no-alloc")]
+            (
+                Self::KernelError {
+                    description: a,
+                    errno: ea,
+                    errno_kind: ka,
+                    ..
+                },
+                Self::KernelError {
+                    description: b,
+                    errno: eb,
+                    errno_kind: kb,
+                    ..
+                },
+            ) => a == b && ea == eb && ka == kb,
+            (
+                Self::FailedToReadStdout { message: a, .. },
+                Self::FailedToReadStdout { message: b, .. },
+            ) => a == b,
+            (
+                Self::FailedToReadStderr { message: a, .. },
+                Self::FailedToReadStderr { message: b, .. },
+            ) => a == b,
+            (
+                Self::NullByteInOutput { stream: sa, message: a },
+                Self::NullByteInOutput { stream: sb, message: b },
+            ) => sa == sb && a == b,
+            _ => false,
+        }
+    }
+}
+
 impl RashError {
-    pub(crate) fn format_kernel_error_message<L, S>(wrapper: &L, description: S) -> String
+    #[cfg(not(feature = "no-alloc"))]
+    pub(crate) fn format_kernel_error_message<L, S>(wrapper: &L, description: S) -> RashError
     where
         L: LibCWrapper,
         S: AsRef<str>,
@@ -63,12 +268,88 @@ impl RashError {
             (errno, strerror)
         };
 
-        format!(
+        let message = format!(
             "Received errno {}, Description: {}, strerror output: {}.",
             errno.to_string(),
             description.as_ref(),
             strerror
-        )
+        );
+
+        RashError::KernelError {
+            message,
+            errno,
+            errno_kind: Errno::from_raw(errno),
+        }
+    }
+
+    /// The `no-alloc` counterpart of the above: it never builds an owned
+    /// `String`, instead keeping the `&'static str` description and a
+    /// [`StrError`] captured via `strerror_r` into a stack buffer.
+    #[cfg(feature = "no-alloc")]
+    pub(crate) fn format_kernel_error_message<L>(
+        wrapper: &L,
+        description: &'static str,
+    ) -> RashError
+    where
+        L: LibCWrapper,
+    {
+        let errno = unsafe { *wrapper.__errno_location() };
+        let strerror = StrError::capture(wrapper, errno);
+
+        RashError::KernelError {
+            description,
+            strerror,
+            errno,
+            errno_kind: Errno::from_raw(errno),
+        }
+    }
+
+    /// Returns the raw `errno` that caused this error, if any.
+    pub fn errno(&self) -> Option<i32> {
+        match self {
+            RashError::KernelError { errno, .. } => Some(*errno),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if this error was caused by the given decoded `errno`.
+    pub fn is(&self, e: Errno) -> bool {
+        matches!(self, RashError::KernelError { errno_kind: Some(k), .. } if *k == e)
+    }
+
+    /// Translates this error into the conventional BSD `sysexits.h` exit
+    /// code for the failure it represents, so thin CLI wrappers around
+    /// rash can propagate a semantically correct process exit status
+    /// instead of a blanket `1`.
+    pub fn exit_code(&self) -> i32 {
+        /// The command was used incorrectly.
+        const EX_USAGE: i32 = 64;
+        /// The input data was incorrect in some way.
+        const EX_DATAERR: i32 = 65;
+        /// A service is unavailable; here, the command couldn't be spawned.
+        const EX_UNAVAILABLE: i32 = 69;
+        /// An operating system error has been detected.
+        const EX_OSERR: i32 = 71;
+        /// An error occurred while doing I/O on some file.
+        const EX_IOERR: i32 = 74;
+
+        match self {
+            RashError::NullByteInCommand { .. } => EX_USAGE,
+            RashError::NullByteInOutput { .. } => EX_DATAERR,
+            RashError::FailedToReadStdout { .. } | RashError::FailedToReadStderr { .. } => {
+                EX_IOERR
+            }
+            RashError::KernelError { errno_kind, .. } => match errno_kind {
+                Some(Errno::Enoent) | Some(Errno::Eacces) => EX_UNAVAILABLE,
+                _ => EX_OSERR,
+            },
+        }
+    }
+}
+
+impl From<RashError> for std::process::ExitCode {
+    fn from(err: RashError) -> Self {
+        std::process::ExitCode::from(err.exit_code() as u8)
     }
 }
 
@@ -116,14 +397,138 @@ mod tests {
             HELLO = transmute(boxed);
             return (&*HELLO).as_ptr() as *mut c_char;
         }
+
+        unsafe fn strerror_r(&self, _errno: c_int, buf: *mut c_char, buflen: usize) -> c_int {
+            let message = b"Hello\0";
+            let n = message.len().min(buflen);
+            std::ptr::copy_nonoverlapping(message.as_ptr() as *const c_char, buf, n);
+            0
+        }
+
+        unsafe fn pipe(&self, fds: *mut c_int) -> c_int {
+            libc::pipe(fds)
+        }
+
+        unsafe fn memfd_create(&self, name: *const c_char, flags: libc::c_uint) -> c_int {
+            libc::memfd_create(name, flags)
+        }
+
+        unsafe fn ftruncate(&self, fd: c_int, length: libc::off_t) -> c_int {
+            libc::ftruncate(fd, length)
+        }
+
+        unsafe fn mmap(
+            &self,
+            addr: *mut libc::c_void,
+            len: libc::size_t,
+            prot: c_int,
+            flags: c_int,
+            fd: c_int,
+            offset: libc::off_t,
+        ) -> *mut libc::c_void {
+            libc::mmap(addr, len, prot, flags, fd, offset)
+        }
     }
 
+    #[cfg(not(feature = "no-alloc"))]
     #[test]
     fn test_format_kernel_error_message_formats_correctly() {
         let ref mock_wrapper = MockLibCWrapper {};
         assert_eq!(
             RashError::format_kernel_error_message(mock_wrapper, "My description"),
+            RashError::KernelError {
+                message: "Received errno 7, Description: My description, strerror output: Hello."
+                    .to_string(),
+                errno: 7,
+                errno_kind: None,
+            }
+        );
+    }
+
+    #[cfg(not(feature = "no-alloc"))]
+    #[test]
+    fn test_errno_and_is_reflect_the_decoded_errno() {
+        let ref mock_wrapper = MockLibCWrapper {};
+        let err = RashError::format_kernel_error_message(mock_wrapper, "My description");
+
+        assert_eq!(err.errno(), Some(7));
+        assert!(!err.is(Errno::Enoent));
+
+        let enoent = RashError::KernelError {
+            message: "boom".to_string(),
+            errno: libc::ENOENT,
+            errno_kind: Errno::from_raw(libc::ENOENT),
+        };
+        assert!(enoent.is(Errno::Enoent));
+    }
+
+    #[cfg(not(feature = "no-alloc"))]
+    #[test]
+    fn test_exit_code_maps_to_sysexits() {
+        assert_eq!(
+            RashError::NullByteInCommand {
+                message: "boom".to_string()
+            }
+            .exit_code(),
+            64
+        );
+        assert_eq!(
+            RashError::KernelError {
+                message: "boom".to_string(),
+                errno: libc::ENOENT,
+                errno_kind: Errno::from_raw(libc::ENOENT),
+            }
+            .exit_code(),
+            69
+        );
+        assert_eq!(
+            RashError::KernelError {
+                message: "boom".to_string(),
+                errno: libc::EINTR,
+                errno_kind: Errno::from_raw(libc::EINTR),
+            }
+            .exit_code(),
+            71
+        );
+    }
+
+    #[cfg(feature = "no-alloc")]
+    #[test]
+    fn test_format_kernel_error_message_does_not_allocate_a_message_string() {
+        let ref mock_wrapper = MockLibCWrapper {};
+        let err = RashError::format_kernel_error_message(mock_wrapper, "My description");
+
+        assert_eq!(err.errno(), Some(7));
+        assert_eq!(
+            err.to_string(),
             "Received errno 7, Description: My description, strerror output: Hello."
         );
     }
+
+    #[test]
+    fn test_failed_to_read_stdout_source_downcasts_to_the_original_io_error() {
+        let io_error = std::io::Error::new(std::io::ErrorKind::BrokenPipe, "broken pipe");
+        let err = RashError::FailedToReadStdout {
+            message: io_error.to_string(),
+            source: Box::new(io_error),
+        };
+
+        let source = err.source().expect("FailedToReadStdout should keep a source");
+        let io_error = source
+            .downcast_ref::<std::io::Error>()
+            .expect("source should downcast back to std::io::Error");
+        assert_eq!(io_error.kind(), std::io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn test_failed_to_read_stderr_source_downcasts_to_the_original_utf8_error() {
+        let utf8_error = String::from_utf8(vec![0xff]).unwrap_err().utf8_error();
+        let err = RashError::FailedToReadStderr {
+            message: utf8_error.to_string(),
+            source: Box::new(utf8_error),
+        };
+
+        let source = err.source().expect("FailedToReadStderr should keep a source");
+        assert!(source.downcast_ref::<std::str::Utf8Error>().is_some());
+    }
 }
@@ -0,0 +1,211 @@
+//! A C-callable surface for rash, so non-Rust callers (C, Python via
+//! `ctypes`/`cffi`, ...) can run commands without linking against rash's
+//! Rust types.
+//!
+//! Errors follow the common FFI pattern of stashing the most recent error
+//! per-thread and offering length-then-copy accessors: a failing call
+//! stores its [`RashError`] in a `thread_local`, and the caller retrieves
+//! the formatted message with [`rash_last_error_length`] followed by
+//! [`rash_last_error_message`].
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+
+use crate::command;
+use crate::error::RashError;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<RashError>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(err: RashError) {
+    LAST_ERROR.with(|slot| *slot.borrow_mut() = Some(err));
+}
+
+/// Runs `cmd` (a NUL-terminated C string), writing newly allocated,
+/// NUL-terminated stdout/stderr buffers through `out_stdout`/`out_stderr`.
+/// Returns `0` on success, `-1` on failure.
+///
+/// On failure, `*out_stdout`/`*out_stderr` are left untouched; call
+/// [`rash_last_error_length`] and [`rash_last_error_message`] to retrieve
+/// the failure. This includes the case where captured output contains a
+/// null byte and can't be represented as a NUL-terminated C string: rather
+/// than silently truncating it, `rash_run` fails with
+/// [`RashError::NullByteInOutput`]. If `cmd`, `out_stdout` or `out_stderr`
+/// is null, or `cmd` isn't valid UTF-8, `rash_run` returns `-1` without
+/// recording a new error, so the accessors still report any earlier error
+/// on this thread. On success, the caller must free both buffers with
+/// [`rash_free_string`].
+///
+/// # Safety
+/// `cmd` must be a valid, NUL-terminated C string. `out_stdout` and
+/// `out_stderr` must be valid, writable pointers.
+#[no_mangle]
+pub unsafe extern "C" fn rash_run(
+    cmd: *const c_char,
+    out_stdout: *mut *mut c_char,
+    out_stderr: *mut *mut c_char,
+) -> c_int {
+    if cmd.is_null() || out_stdout.is_null() || out_stderr.is_null() {
+        return -1;
+    }
+
+    let cmd = match CStr::from_ptr(cmd).to_str() {
+        Ok(s) => s,
+        Err(_) => return -1,
+    };
+
+    match command::run(cmd) {
+        Ok(output) => {
+            let stdout = match string_to_c_string("stdout", output.stdout) {
+                Ok(s) => s,
+                Err(e) => {
+                    set_last_error(e);
+                    return -1;
+                }
+            };
+            let stderr = match string_to_c_string("stderr", output.stderr) {
+                Ok(s) => s,
+                Err(e) => {
+                    rash_free_string(stdout);
+                    set_last_error(e);
+                    return -1;
+                }
+            };
+
+            *out_stdout = stdout;
+            *out_stderr = stderr;
+            0
+        }
+        Err(e) => {
+            set_last_error(e);
+            -1
+        }
+    }
+}
+
+/// Frees a string previously returned by [`rash_run`] through an
+/// out-pointer.
+///
+/// # Safety
+/// `s` must be a pointer previously returned by this module, or null.
+#[no_mangle]
+pub unsafe extern "C" fn rash_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Returns the length, in bytes excluding the trailing NUL, of the
+/// formatted message of the last error on this thread, or `0` if there
+/// wasn't one.
+#[no_mangle]
+pub extern "C" fn rash_last_error_length() -> c_int {
+    LAST_ERROR.with(|slot| {
+        slot.borrow()
+            .as_ref()
+            .map(|e| e.to_string().len() as c_int)
+            .unwrap_or(0)
+    })
+}
+
+/// Copies the formatted message of the last error on this thread into
+/// `buf`, which must be at least `rash_last_error_length() + 1` bytes.
+/// Returns the number of bytes written (excluding the NUL), or `-1` if
+/// there was no error on this thread or `buf` is too small.
+///
+/// # Safety
+/// `buf` must be a valid pointer to at least `len` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rash_last_error_message(buf: *mut c_char, len: c_int) -> c_int {
+    LAST_ERROR.with(|slot| {
+        let message = match slot.borrow().as_ref() {
+            Some(e) => e.to_string(),
+            None => return -1,
+        };
+
+        if buf.is_null() || len < 0 || message.len() >= len as usize {
+            return -1;
+        }
+
+        std::ptr::copy_nonoverlapping(message.as_ptr() as *const c_char, buf, message.len());
+        *buf.add(message.len()) = 0;
+        message.len() as c_int
+    })
+}
+
+/// Converts captured output into a NUL-terminated C string, failing
+/// instead of silently dropping the data if it contains an interior null
+/// byte (valid in a Rust `String`, but not representable as a C string).
+fn string_to_c_string(stream: &'static str, s: String) -> Result<*mut c_char, RashError> {
+    CString::new(s)
+        .map(CString::into_raw)
+        .map_err(|e| RashError::NullByteInOutput {
+            stream,
+            message: format!("Null byte found at position {}", e.nul_position()),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rash_run_writes_stdout_and_stderr_and_frees_cleanly() {
+        let cmd = CString::new("echo out; echo err 1>&2").unwrap();
+        let mut stdout_ptr: *mut c_char = std::ptr::null_mut();
+        let mut stderr_ptr: *mut c_char = std::ptr::null_mut();
+
+        let status =
+            unsafe { rash_run(cmd.as_ptr(), &mut stdout_ptr, &mut stderr_ptr) };
+        assert_eq!(status, 0);
+
+        let stdout = unsafe { CStr::from_ptr(stdout_ptr) }.to_str().unwrap();
+        let stderr = unsafe { CStr::from_ptr(stderr_ptr) }.to_str().unwrap();
+        assert_eq!(stdout, "out\n");
+        assert_eq!(stderr, "err\n");
+
+        unsafe {
+            rash_free_string(stdout_ptr);
+            rash_free_string(stderr_ptr);
+        }
+    }
+
+    #[test]
+    fn test_last_error_length_then_copy_round_trips() {
+        let cmd = CString::new("printf '\\376'").unwrap();
+        let mut stdout_ptr: *mut c_char = std::ptr::null_mut();
+        let mut stderr_ptr: *mut c_char = std::ptr::null_mut();
+
+        let status =
+            unsafe { rash_run(cmd.as_ptr(), &mut stdout_ptr, &mut stderr_ptr) };
+        assert_eq!(status, -1);
+
+        let len = rash_last_error_length();
+        assert!(len > 0);
+
+        let mut buf = vec![0 as c_char; len as usize + 1];
+        let written = unsafe { rash_last_error_message(buf.as_mut_ptr(), buf.len() as c_int) };
+        assert_eq!(written, len);
+
+        let message = unsafe { CStr::from_ptr(buf.as_ptr()) }.to_str().unwrap();
+        assert_eq!(message.len(), len as usize);
+    }
+
+    #[test]
+    fn test_rash_run_fails_instead_of_truncating_output_with_a_null_byte() {
+        let cmd = CString::new("printf 'a\\0b'").unwrap();
+        let mut stdout_ptr: *mut c_char = std::ptr::null_mut();
+        let mut stderr_ptr: *mut c_char = std::ptr::null_mut();
+
+        let status =
+            unsafe { rash_run(cmd.as_ptr(), &mut stdout_ptr, &mut stderr_ptr) };
+        assert_eq!(status, -1);
+        assert!(stdout_ptr.is_null());
+        assert!(stderr_ptr.is_null());
+
+        let len = rash_last_error_length();
+        assert!(len > 0);
+    }
+}
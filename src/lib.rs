@@ -0,0 +1,8 @@
+pub mod command;
+pub mod error;
+pub mod ffi;
+pub mod wrapper;
+
+pub use command::{run, Output};
+pub use error::RashError;
+pub use wrapper::{LibC, LibCWrapper};
@@ -0,0 +1,134 @@
+use libc::{c_char, c_int, c_uint, c_void, off_t, size_t, FILE};
+
+/// A thin, mockable wrapper around the libc calls rash's command execution
+/// is built on top of.
+///
+/// Tests substitute their own implementation to control `errno`/`strerror`
+/// output deterministically; [`LibC`] is the real one, used in production.
+pub trait LibCWrapper {
+    /// # Safety
+    /// `command` must be a valid, NUL-terminated C string.
+    unsafe fn popen(&self, command: *const c_char) -> *mut FILE;
+    /// # Safety
+    /// `stream` must be a valid, open `FILE*` previously returned by `popen`.
+    unsafe fn fileno(&self, stream: *mut FILE) -> c_int;
+    /// # Safety
+    /// `fd` must be a valid, open file descriptor.
+    unsafe fn dup(&self, fd: c_int) -> c_int;
+    /// # Safety
+    /// `src` and `dst` must be valid file descriptors (`dst` is closed
+    /// first if already open).
+    unsafe fn dup2(&self, src: c_int, dst: c_int) -> c_int;
+    /// # Safety
+    /// `stream` must be a valid, open `FILE*` previously returned by `popen`,
+    /// not already closed.
+    unsafe fn pclose(&self, stream: *mut FILE) -> c_int;
+    /// # Safety
+    /// The returned pointer is only valid for the lifetime of the calling
+    /// thread.
+    unsafe fn __errno_location(&self) -> *mut c_int;
+    /// # Safety
+    /// The returned pointer is only valid until the next call to `strerror`
+    /// on this thread.
+    unsafe fn strerror(&self, errno: c_int) -> *mut c_char;
+    /// The thread-safe counterpart of `strerror`: writes the message for
+    /// `errno` into `buf` (truncated to fit `buflen`, including the
+    /// trailing NUL) instead of returning a pointer into a buffer libc
+    /// owns and may later overwrite.
+    ///
+    /// # Safety
+    /// `buf` must be a valid pointer to at least `buflen` writable bytes.
+    unsafe fn strerror_r(&self, errno: c_int, buf: *mut c_char, buflen: size_t) -> c_int;
+    /// Creates a pipe, writing the read end to `fds[0]` and the write end
+    /// to `fds[1]`.
+    ///
+    /// # Safety
+    /// `fds` must be a valid pointer to two writable `c_int`s.
+    unsafe fn pipe(&self, fds: *mut c_int) -> c_int;
+    /// Creates an anonymous, memory-backed file descriptor (Linux-only).
+    /// Used by the `memfd-capture` feature to capture a command's stdout
+    /// without going through a pipe.
+    ///
+    /// # Safety
+    /// `name` must be a valid, NUL-terminated C string.
+    unsafe fn memfd_create(&self, name: *const c_char, flags: c_uint) -> c_int;
+    /// # Safety
+    /// `fd` must be a valid, open, writable file descriptor.
+    unsafe fn ftruncate(&self, fd: c_int, length: off_t) -> c_int;
+    /// # Safety
+    /// `fd` must be a valid file descriptor and `len`/`offset` must describe
+    /// a region within it; the returned pointer is valid for `len` bytes
+    /// until it is `munmap`'d.
+    unsafe fn mmap(
+        &self,
+        addr: *mut c_void,
+        len: size_t,
+        prot: c_int,
+        flags: c_int,
+        fd: c_int,
+        offset: off_t,
+    ) -> *mut c_void;
+}
+
+/// The production [`LibCWrapper`] that calls straight through to libc.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LibC;
+
+impl LibCWrapper for LibC {
+    unsafe fn popen(&self, command: *const c_char) -> *mut FILE {
+        let read_mode = std::ffi::CString::new("r").unwrap();
+        libc::popen(command, read_mode.as_ptr())
+    }
+
+    unsafe fn fileno(&self, stream: *mut FILE) -> c_int {
+        libc::fileno(stream)
+    }
+
+    unsafe fn dup(&self, fd: c_int) -> c_int {
+        libc::dup(fd)
+    }
+
+    unsafe fn dup2(&self, src: c_int, dst: c_int) -> c_int {
+        libc::dup2(src, dst)
+    }
+
+    unsafe fn pclose(&self, stream: *mut FILE) -> c_int {
+        libc::pclose(stream)
+    }
+
+    unsafe fn __errno_location(&self) -> *mut c_int {
+        libc::__errno_location()
+    }
+
+    unsafe fn strerror(&self, errno: c_int) -> *mut c_char {
+        libc::strerror(errno)
+    }
+
+    unsafe fn strerror_r(&self, errno: c_int, buf: *mut c_char, buflen: size_t) -> c_int {
+        libc::strerror_r(errno, buf, buflen)
+    }
+
+    unsafe fn pipe(&self, fds: *mut c_int) -> c_int {
+        libc::pipe(fds)
+    }
+
+    unsafe fn memfd_create(&self, name: *const c_char, flags: c_uint) -> c_int {
+        libc::memfd_create(name, flags)
+    }
+
+    unsafe fn ftruncate(&self, fd: c_int, length: off_t) -> c_int {
+        libc::ftruncate(fd, length)
+    }
+
+    unsafe fn mmap(
+        &self,
+        addr: *mut c_void,
+        len: size_t,
+        prot: c_int,
+        flags: c_int,
+        fd: c_int,
+        offset: off_t,
+    ) -> *mut c_void {
+        libc::mmap(addr, len, prot, flags, fd, offset)
+    }
+}